@@ -1,66 +1,111 @@
-use std::cell::{Cell, RefCell};
+#[cfg(test)]
+use std::cell::Cell;
+#[cfg(test)]
+use std::collections::VecDeque;
+use std::cell::RefCell;
 use std::{convert::TryInto, thread, time::{Duration, SystemTime, UNIX_EPOCH}};
 
+use crate::SnowflakeError;
+
 thread_local! {
-    static CLOCK: RefCell<Box<dyn Clock>> = RefCell::new(Box::new(SystemTimeClock(Cell::new(0))));
+    static CLOCK: RefCell<Box<dyn Clock>> = RefCell::new(Box::new(SystemTimeClock));
 }
 
-/// A trait for a clock that can get the time (in milliseconds since a given epoch) and 
+/// A trait for a clock that can get the time (in milliseconds since a given epoch) and
 /// will wait for the next millisecond.
 pub trait Clock {
-    fn get_time(&self) -> u64;
+    fn get_time(&self, epoch: u64) -> Result<u64, SnowflakeError>;
 
     fn wait(&self);
+
+    /// Force the clock to a specific time. Only meaningful for test clocks;
+    /// real clocks ignore it.
+    #[cfg(test)]
+    fn set_time(&self, _ts: u64) {}
+
+    /// Queue a one-shot reading to return from the *next* call to `get_time`,
+    /// regardless of `set_time`. Lets a test script the two internal clock
+    /// reads within a single `try_generate` call independently, e.g. to
+    /// simulate the wall clock dropping between them. Only meaningful for
+    /// test clocks; real clocks ignore it.
+    #[cfg(test)]
+    fn queue_time(&self, _ts: u64) {}
+
+    /// Slew-wait until the clock reads at least `target_ms`, polling via `wait`.
+    fn wait_until(&self, epoch: u64, target_ms: u64) -> Result<(), SnowflakeError> {
+        while self.get_time(epoch)? < target_ms {
+            self.wait();
+        }
+        Ok(())
+    }
 }
 
-/// A Clock implementation that uses SystemTime to provide millisecond precision using 2020-01-01 as the epoch.
-struct SystemTimeClock(Cell<u64>);
+/// A Clock implementation that uses SystemTime to provide millisecond precision against a caller-supplied epoch.
+///
+/// `SystemTime::now` is not guaranteed to be monotonically increasing, so this
+/// reports the wall clock as-is, including any backwards jump; it's up to
+/// `Snowflake` to decide how to handle that (clamp, slew-wait, or error via
+/// `max_backward_drift`).
+struct SystemTimeClock;
 
 /// A Clock implementation that can be used for tests. Time will not progress unless wait is called, then 1 ms will pass.
-struct MockClock(Cell<u64>);
+/// `scripted` holds one-shot overrides (see `queue_time`) consumed oldest-first
+/// ahead of the live `now` value, for tests that need to control two
+/// consecutive reads within a single `try_generate` call independently.
+#[cfg(test)]
+struct MockClock {
+    now: Cell<u64>,
+    scripted: RefCell<VecDeque<u64>>,
+}
 
 impl Clock for SystemTimeClock {
-    fn get_time(&self) -> u64 {
-        const EPOCH: u128 = 1577836800000; // 2020-01-01T00:00:00Z
+    fn get_time(&self, epoch: u64) -> Result<u64, SnowflakeError> {
         let tm = SystemTime::now();
 
         let millis = match tm.duration_since(UNIX_EPOCH) {
             Ok(n) => n.as_millis(),
-            Err(_) => panic!("System time is before UNIX_EPOCH"),
+            Err(_) => return Err(SnowflakeError::ClockBeforeEpoch),
         };
 
-        let adj_ms = millis - EPOCH;
-        let new_ts = adj_ms.try_into().unwrap();
-
-        // SystemTime::now is not guarenteed to be monotonically increasing, but 
-        // the Snowflake requires it to be. If the new ts is not greater than the
-        // old, then we just use the old ts.
-        if new_ts > self.0.get() {
-            self.0.set(new_ts);
+        if millis < epoch as u128 {
+            return Err(SnowflakeError::ClockBeforeEpoch);
         }
 
-        self.0.get()
+        let adj_ms = millis - epoch as u128;
+        adj_ms.try_into().map_err(|_| SnowflakeError::TimestampOverflow)
     }
 
     fn wait(&self) {
-        thread::sleep(Duration::from_millis(1)) 
+        thread::sleep(Duration::from_millis(1))
     }
 }
 
+#[cfg(test)]
 impl Clock for MockClock {
-    fn get_time(&self) -> u64 {
-        self.0.get()
+    fn get_time(&self, _epoch: u64) -> Result<u64, SnowflakeError> {
+        if let Some(ts) = self.scripted.borrow_mut().pop_front() {
+            return Ok(ts);
+        }
+        Ok(self.now.get())
     }
 
     fn wait(&self) {
-        let ts = self.0.get();
-        self.0.set(ts + 1);
+        let ts = self.now.get();
+        self.now.set(ts + 1);
+    }
+
+    fn set_time(&self, ts: u64) {
+        self.now.set(ts);
+    }
+
+    fn queue_time(&self, ts: u64) {
+        self.scripted.borrow_mut().push_back(ts);
     }
 }
 
-pub fn get_time() -> u64 {
+pub fn get_time(epoch: u64) -> Result<u64, SnowflakeError> {
     CLOCK.with(|c| {
-        c.borrow().get_time()
+        c.borrow().get_time(epoch)
     })
 }
 
@@ -70,31 +115,59 @@ pub fn wait() {
     })
 }
 
+pub fn wait_until(epoch: u64, target_ms: u64) -> Result<(), SnowflakeError> {
+    CLOCK.with(|c| {
+        c.borrow().wait_until(epoch, target_ms)
+    })
+}
+
 /// Set the mock clock to be used.
+#[cfg(test)]
 pub fn setup_mock_clock() {
     CLOCK.with(|c| {
-        let ts = c.borrow().get_time();
-        let mock = Box::new(MockClock(Cell::new(ts)));
+        let ts = c.borrow().get_time(0).unwrap_or(0);
+        let mock = Box::new(MockClock { now: Cell::new(ts), scripted: RefCell::new(VecDeque::new()) });
         *c.borrow_mut() = mock;
     })
 }
 
+/// Force the current clock (the mock clock, in practice) to `ts`. Used by tests
+/// that need to simulate the wall clock jumping backwards.
+#[cfg(test)]
+pub fn set_mock_time(ts: u64) {
+    CLOCK.with(|c| {
+        c.borrow().set_time(ts)
+    })
+}
+
+/// Queue a one-shot reading for the mock clock's next `get_time` call. Used by
+/// tests that need to script the two internal clock reads within a single
+/// `try_generate` call independently (e.g. a drop that happens between them).
+#[cfg(test)]
+pub fn queue_mock_time(ts: u64) {
+    CLOCK.with(|c| {
+        c.borrow().queue_time(ts)
+    })
+}
+
+/// A small non-cryptographic seed, used to randomize a generator's initial
+/// clock-sequence value so that restarts on the same node don't all start at 0.
+pub fn random_u16() -> u16 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u16)
+        .unwrap_or(0)
+}
+
 #[cfg(test)]
 mod tests {
-    use std::cell::{Cell};
     use crate::clock::{Clock, SystemTimeClock};
 
     #[test]
-    fn system_clock_never_goes_backwards() {
-        let clock = SystemTimeClock(Cell::new(0));
-
-        let last = clock.get_time();
-        for _ in 0..10000 {
-            let now = clock.get_time();
-            assert!(now >= last);
-        }
-
+    fn system_clock_reports_milliseconds_since_epoch() {
+        let clock = SystemTimeClock;
+        let before = clock.get_time(0).unwrap();
+        let after = clock.get_time(0).unwrap();
+        assert!(after >= before);
     }
-
-    
 }
@@ -0,0 +1,79 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{Snowflake, SnowflakeConfig, SnowflakeError};
+
+/// A [`Snowflake`] generator shared across threads.
+///
+/// `Snowflake::generate` takes `&mut self`, so a single generator can't be used
+/// concurrently without giving every caller its own `node_id`. `ConcurrentSnowflake`
+/// wraps one generator in an `Arc<Mutex<_>>` so many tasks on the same node can
+/// pull monotonically increasing IDs from a single shared instance.
+#[derive(Debug, Clone)]
+pub struct ConcurrentSnowflake {
+    inner: Arc<Mutex<Snowflake>>,
+}
+
+impl ConcurrentSnowflake {
+    /// Wrap a generator using the classic Twitter Snowflake layout.
+    pub fn new(node_id: u16) -> Result<ConcurrentSnowflake, SnowflakeError> {
+        Ok(ConcurrentSnowflake::from(Snowflake::new(node_id)?))
+    }
+
+    /// Wrap a generator using a custom bit-layout.
+    pub fn with_config(node_id: u16, config: SnowflakeConfig) -> Result<ConcurrentSnowflake, SnowflakeError> {
+        Ok(ConcurrentSnowflake::from(Snowflake::with_config(node_id, config)?))
+    }
+
+    /// Generate the next id, locking the shared generator internally.
+    pub fn generate(&self) -> u64 {
+        self.inner.lock().unwrap().generate()
+    }
+
+    /// Generate the next id, surfacing clock and overflow failures instead of panicking.
+    pub fn try_generate(&self) -> Result<u64, SnowflakeError> {
+        self.inner.lock().unwrap().try_generate()
+    }
+
+    /// Parse an id produced by this generator using its config.
+    pub fn parse_id(&self, id: u64) -> Snowflake {
+        self.inner.lock().unwrap().parse_id(id)
+    }
+}
+
+impl From<Snowflake> for ConcurrentSnowflake {
+    fn from(snowflake: Snowflake) -> Self {
+        ConcurrentSnowflake {
+            inner: Arc::new(Mutex::new(snowflake)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn shared_across_threads_yields_unique_ids() {
+        let gen = ConcurrentSnowflake::new(42).unwrap();
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let gen = gen.clone();
+                thread::spawn(move || {
+                    (0..1000).map(move |_| gen.generate()).collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        let mut ids: Vec<u64> = handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect();
+
+        ids.sort_unstable();
+        let unique = ids.len();
+        ids.dedup();
+        assert_eq!(ids.len(), unique, "generator produced duplicate ids");
+    }
+}
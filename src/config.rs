@@ -0,0 +1,337 @@
+use crate::SnowflakeError;
+
+/// The default epoch used when none is configured: 2020-01-01T00:00:00Z, in
+/// milliseconds since the Unix epoch.
+pub const DEFAULT_EPOCH: u64 = 1577836800000;
+
+const DEFAULT_TIMESTAMP_BITS: u8 = 41;
+const DEFAULT_NODE_BITS: u8 = 10;
+const DEFAULT_SEQUENCE_BITS: u8 = 12;
+const DEFAULT_CLOCK_SEQ_BITS: u8 = 0;
+const DEFAULT_DATACENTER_BITS: u8 = 0;
+
+/// The bit-layout and epoch a [`Snowflake`](crate::Snowflake) uses to build and parse IDs.
+///
+/// An ID is laid out, from the most significant bit down, as
+/// `[ timestamp_bits | node_bits | clock_seq_bits | sequence_bits ]`, with the
+/// timestamp measured in milliseconds since `epoch`. `clock_seq_bits` defaults to
+/// 0, shrinking the layout back to the classic three-field one. Build a config
+/// with [`SnowflakeConfig::builder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnowflakeConfig {
+    pub(crate) epoch: u64,
+    pub(crate) timestamp_bits: u8,
+    pub(crate) node_bits: u8,
+    pub(crate) datacenter_bits: u8,
+    pub(crate) clock_seq_bits: u8,
+    pub(crate) sequence_bits: u8,
+    pub(crate) max_backward_drift: Option<u64>,
+}
+
+impl SnowflakeConfig {
+    /// Start building a config, pre-filled with the classic Twitter Snowflake
+    /// layout (41/10/12 bits) and a 2020-01-01 epoch.
+    pub fn builder() -> SnowflakeConfigBuilder {
+        SnowflakeConfigBuilder::default()
+    }
+
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    pub fn timestamp_bits(&self) -> u8 {
+        self.timestamp_bits
+    }
+
+    pub fn node_bits(&self) -> u8 {
+        self.node_bits
+    }
+
+    /// Number of the node field's bits given to `datacenter_id`, the rest going
+    /// to `worker_id`. 0 (the default) means the whole node field is `worker_id`.
+    pub fn datacenter_bits(&self) -> u8 {
+        self.datacenter_bits
+    }
+
+    /// Number of the node field's bits given to `worker_id`.
+    pub fn worker_bits(&self) -> u8 {
+        self.node_bits - self.datacenter_bits
+    }
+
+    /// Number of bits carved out for the clock-sequence field. 0 if unused.
+    pub fn clock_seq_bits(&self) -> u8 {
+        self.clock_seq_bits
+    }
+
+    pub fn sequence_bits(&self) -> u8 {
+        self.sequence_bits
+    }
+
+    /// The largest sequence number that fits in `sequence_bits`.
+    pub fn max_seq_num(&self) -> u16 {
+        ((1u32 << self.sequence_bits) - 1) as u16
+    }
+
+    /// The largest node id that fits in `node_bits`.
+    pub fn max_node_id(&self) -> u16 {
+        ((1u32 << self.node_bits) - 1) as u16
+    }
+
+    /// The largest `datacenter_id` that fits in `datacenter_bits`. 0 if unused.
+    pub fn max_datacenter_id(&self) -> u16 {
+        if self.datacenter_bits == 0 {
+            0
+        } else {
+            ((1u32 << self.datacenter_bits) - 1) as u16
+        }
+    }
+
+    /// The largest `worker_id` that fits in `worker_bits`.
+    pub fn max_worker_id(&self) -> u16 {
+        ((1u32 << self.worker_bits()) - 1) as u16
+    }
+
+    /// The largest clock-sequence value that fits in `clock_seq_bits`. 0 if unused.
+    pub fn max_clock_seq(&self) -> u16 {
+        if self.clock_seq_bits == 0 {
+            0
+        } else {
+            ((1u32 << self.clock_seq_bits) - 1) as u16
+        }
+    }
+
+    /// How far (in milliseconds) the wall clock may read behind the generator's
+    /// last timestamp before generation fails with `ClockMovedBackwards`. `None`
+    /// (the default) tolerates any amount of drift: the generator clamps to its
+    /// last timestamp or, when `clock_seq_bits` is set, bumps `clock_seq` and
+    /// adopts the earlier time.
+    pub fn max_backward_drift(&self) -> Option<u64> {
+        self.max_backward_drift
+    }
+}
+
+impl Default for SnowflakeConfig {
+    fn default() -> Self {
+        SnowflakeConfigBuilder::default().build().expect("the default bit layout is always valid")
+    }
+}
+
+/// Builder for [`SnowflakeConfig`].
+#[derive(Debug, Clone, Copy)]
+pub struct SnowflakeConfigBuilder {
+    epoch: u64,
+    timestamp_bits: u8,
+    node_bits: u8,
+    datacenter_bits: u8,
+    clock_seq_bits: u8,
+    sequence_bits: u8,
+    max_backward_drift: Option<u64>,
+}
+
+impl SnowflakeConfigBuilder {
+    /// Use `epoch` (milliseconds since the Unix epoch) as the generator's zero point.
+    pub fn epoch(mut self, epoch: u64) -> Self {
+        self.epoch = epoch;
+        self
+    }
+
+    /// Number of bits given to the timestamp field.
+    pub fn timestamp_bits(mut self, bits: u8) -> Self {
+        self.timestamp_bits = bits;
+        self
+    }
+
+    /// Number of bits given to the node field.
+    pub fn node_bits(mut self, bits: u8) -> Self {
+        self.node_bits = bits;
+        self
+    }
+
+    /// Number of the node field's bits to reserve for `datacenter_id`; the rest
+    /// is `worker_id`. Used by [`Snowflake::with_datacenter_and_config`].
+    pub fn datacenter_bits(mut self, bits: u8) -> Self {
+        self.datacenter_bits = bits;
+        self
+    }
+
+    /// Number of bits given to the clock-sequence field, used to disambiguate
+    /// IDs across a genuine backwards clock jump (not a mere stall on the
+    /// same millisecond). Wraps, via modulo, within its `2^clock_seq_bits`
+    /// values, so a value repeats after that many regressions. 0 (the
+    /// default) disables it.
+    pub fn clock_seq_bits(mut self, bits: u8) -> Self {
+        self.clock_seq_bits = bits;
+        self
+    }
+
+    /// Number of bits given to the per-millisecond sequence field.
+    pub fn sequence_bits(mut self, bits: u8) -> Self {
+        self.sequence_bits = bits;
+        self
+    }
+
+    /// Tolerate the wall clock reading up to `max_ms` behind the generator's
+    /// last timestamp by slew-waiting for it to catch up, rather than clamping
+    /// to the last timestamp forever. Drift beyond `max_ms` fails generation
+    /// with `ClockMovedBackwards` instead.
+    pub fn max_backward_drift(mut self, max_ms: u64) -> Self {
+        self.max_backward_drift = Some(max_ms);
+        self
+    }
+
+    /// Build the config, validating that the bit widths fit together.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SnowflakeError::InvalidBitLayout`] if
+    /// `timestamp_bits + node_bits + clock_seq_bits + sequence_bits` is greater
+    /// than 63 (the result must fit in an `i64`-safe range of a `u64` id); if
+    /// `datacenter_bits` is greater than `node_bits`; or if `node_bits`,
+    /// `clock_seq_bits` or `sequence_bits` is greater than 16, since `node_id`,
+    /// `clock_seq` and `seq_num` are stored as `u16` and a wider field would
+    /// silently truncate (and overflow the `1u32 << bits` used to compute its max value).
+    pub fn build(self) -> Result<SnowflakeConfig, SnowflakeError> {
+        let total = self.timestamp_bits as u16
+            + self.node_bits as u16
+            + self.clock_seq_bits as u16
+            + self.sequence_bits as u16;
+        if total > 63
+            || self.datacenter_bits > self.node_bits
+            || self.node_bits > 16
+            || self.clock_seq_bits > 16
+            || self.sequence_bits > 16
+        {
+            return Err(SnowflakeError::InvalidBitLayout);
+        }
+
+        Ok(SnowflakeConfig {
+            epoch: self.epoch,
+            timestamp_bits: self.timestamp_bits,
+            node_bits: self.node_bits,
+            datacenter_bits: self.datacenter_bits,
+            clock_seq_bits: self.clock_seq_bits,
+            sequence_bits: self.sequence_bits,
+            max_backward_drift: self.max_backward_drift,
+        })
+    }
+}
+
+impl Default for SnowflakeConfigBuilder {
+    fn default() -> Self {
+        SnowflakeConfigBuilder {
+            epoch: DEFAULT_EPOCH,
+            timestamp_bits: DEFAULT_TIMESTAMP_BITS,
+            node_bits: DEFAULT_NODE_BITS,
+            datacenter_bits: DEFAULT_DATACENTER_BITS,
+            clock_seq_bits: DEFAULT_CLOCK_SEQ_BITS,
+            sequence_bits: DEFAULT_SEQUENCE_BITS,
+            max_backward_drift: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_matches_classic_layout() {
+        let config = SnowflakeConfig::default();
+        assert_eq!(config.timestamp_bits(), 41);
+        assert_eq!(config.node_bits(), 10);
+        assert_eq!(config.clock_seq_bits(), 0);
+        assert_eq!(config.sequence_bits(), 12);
+        assert_eq!(config.max_seq_num(), 4095);
+        assert_eq!(config.max_node_id(), 1023);
+        assert_eq!(config.max_clock_seq(), 0);
+    }
+
+    #[test]
+    fn build_errors_when_widths_overflow() {
+        let err = SnowflakeConfig::builder()
+            .timestamp_bits(41)
+            .node_bits(12)
+            .sequence_bits(12)
+            .build()
+            .unwrap_err();
+        assert_eq!(err, SnowflakeError::InvalidBitLayout);
+    }
+
+    #[test]
+    fn build_errors_when_node_bits_exceeds_u16() {
+        let err = SnowflakeConfig::builder()
+            .timestamp_bits(20)
+            .node_bits(17)
+            .sequence_bits(12)
+            .build()
+            .unwrap_err();
+        assert_eq!(err, SnowflakeError::InvalidBitLayout);
+    }
+
+    #[test]
+    fn build_errors_when_sequence_bits_exceeds_u16() {
+        let err = SnowflakeConfig::builder()
+            .timestamp_bits(20)
+            .node_bits(10)
+            .sequence_bits(17)
+            .build()
+            .unwrap_err();
+        assert_eq!(err, SnowflakeError::InvalidBitLayout);
+    }
+
+    #[test]
+    fn build_errors_when_clock_seq_bits_exceeds_u16() {
+        let err = SnowflakeConfig::builder()
+            .timestamp_bits(10)
+            .node_bits(10)
+            .clock_seq_bits(17)
+            .sequence_bits(10)
+            .build()
+            .unwrap_err();
+        assert_eq!(err, SnowflakeError::InvalidBitLayout);
+    }
+
+    #[test]
+    fn build_errors_when_datacenter_bits_exceeds_node_bits() {
+        let err = SnowflakeConfig::builder()
+            .node_bits(10)
+            .datacenter_bits(11)
+            .build()
+            .unwrap_err();
+        assert_eq!(err, SnowflakeError::InvalidBitLayout);
+    }
+
+    #[test]
+    fn clock_seq_bits_carve_out_a_field() {
+        let config = SnowflakeConfig::builder()
+            .timestamp_bits(39)
+            .node_bits(10)
+            .clock_seq_bits(4)
+            .sequence_bits(8)
+            .build()
+            .unwrap();
+
+        assert_eq!(config.max_clock_seq(), 15);
+    }
+
+    #[test]
+    fn datacenter_bits_split_the_node_field() {
+        let config = SnowflakeConfig::builder()
+            .node_bits(10)
+            .datacenter_bits(4)
+            .build()
+            .unwrap();
+
+        assert_eq!(config.worker_bits(), 6);
+        assert_eq!(config.max_datacenter_id(), 15);
+        assert_eq!(config.max_worker_id(), 63);
+    }
+
+    #[test]
+    fn max_backward_drift_defaults_to_unlimited() {
+        assert_eq!(SnowflakeConfig::default().max_backward_drift(), None);
+
+        let config = SnowflakeConfig::builder().max_backward_drift(50).build().unwrap();
+        assert_eq!(config.max_backward_drift(), Some(50));
+    }
+}
@@ -0,0 +1,32 @@
+use std::fmt;
+
+/// Errors that can occur while constructing or running a [`crate::Snowflake`] generator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnowflakeError {
+    /// The requested node id does not fit in the configured node field.
+    NodeIdOutOfRange,
+    /// The wall clock reads a time before the generator's configured epoch.
+    ClockBeforeEpoch,
+    /// The elapsed time since the epoch no longer fits in the configured timestamp field.
+    TimestampOverflow,
+    /// The wall clock moved backwards past the generator's last observed timestamp.
+    ClockMovedBackwards,
+    /// The configured bit widths don't fit together: their sum exceeds 63 bits,
+    /// `datacenter_bits` exceeds `node_bits`, or `node_bits`/`clock_seq_bits`/
+    /// `sequence_bits` exceeds 16 (the width of the `u16` fields that hold them).
+    InvalidBitLayout,
+}
+
+impl fmt::Display for SnowflakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnowflakeError::NodeIdOutOfRange => write!(f, "node_id does not fit in the configured node field"),
+            SnowflakeError::ClockBeforeEpoch => write!(f, "system clock reads a time before the generator's epoch"),
+            SnowflakeError::TimestampOverflow => write!(f, "elapsed time since epoch overflows the configured timestamp field"),
+            SnowflakeError::ClockMovedBackwards => write!(f, "system clock moved backwards past the generator's last timestamp"),
+            SnowflakeError::InvalidBitLayout => write!(f, "configured bit widths don't fit together (sum > 63, datacenter_bits > node_bits, or a field > 16 bits)"),
+        }
+    }
+}
+
+impl std::error::Error for SnowflakeError {}
@@ -1,68 +1,218 @@
 use std::convert::TryInto;
 
 mod clock;
+mod concurrent;
+mod config;
+mod error;
 
-
-const MAX_SEQ_NUM: u16 = 4095;
+pub use concurrent::ConcurrentSnowflake;
+pub use config::{SnowflakeConfig, SnowflakeConfigBuilder};
+pub use error::SnowflakeError;
 
 #[derive(Debug)]
 pub struct Snowflake {
     pub node_id: u16,
+    /// The upper `datacenter_bits` of `node_id`. 0 unless the config carves out
+    /// `datacenter_bits`. See [`SnowflakeConfigBuilder::datacenter_bits`].
+    pub datacenter_id: u16,
+    /// The lower `worker_bits` of `node_id`.
+    pub worker_id: u16,
     pub seq_num: u16,
-    pub ts: u64
+    pub ts: u64,
+    /// Disambiguates IDs across a genuine backwards clock jump (the wall
+    /// clock reading behind the last observed time); merely stalling on the
+    /// same millisecond does not bump it. Wraps, via modulo, within its
+    /// `2^clock_seq_bits` values, so a value repeats after that many
+    /// regressions. Always 0 unless the
+    /// config carves out `clock_seq_bits`. See [`SnowflakeConfigBuilder::clock_seq_bits`].
+    pub clock_seq: u16,
+    config: SnowflakeConfig,
+    last_time: u64,
 }
 
-fn create_id(ts: u64, node: u16, seq: u16) -> u64 {
-    let ts_bits = ts << 22;
-    let node_bits = (node as u64) << 12;
+fn create_id(ts: u64, node: u16, clock_seq: u16, seq: u16, config: &SnowflakeConfig) -> u64 {
+    let ts_bits = ts << (config.node_bits() + config.clock_seq_bits() + config.sequence_bits());
+    let node_bits = (node as u64) << (config.clock_seq_bits() + config.sequence_bits());
+    let clock_seq_bits = (clock_seq as u64) << config.sequence_bits();
 
+    ts_bits | node_bits | clock_seq_bits | (seq as u64)
+}
 
-    ts_bits | node_bits | (seq as u64)
+/// Split a composed `node_id` into its `(datacenter_id, worker_id)` sub-fields.
+fn split_node(node_id: u16, config: &SnowflakeConfig) -> (u16, u16) {
+    let worker_id = node_id & config.max_worker_id();
+    let datacenter_id = node_id.checked_shr(config.worker_bits() as u32).unwrap_or(0);
+    (datacenter_id, worker_id)
 }
 
-pub fn parse_id(id: u64) -> Snowflake {
+impl Snowflake {
+
+    /// Create a generator using the classic Twitter Snowflake layout
+    /// (41-bit timestamp / 10-bit node / 12-bit sequence, 2020-01-01 epoch).
+    pub fn new(node_id: u16) -> Result<Snowflake, SnowflakeError> {
+        Snowflake::with_config(node_id, SnowflakeConfig::default())
+    }
+
+    /// Create a generator with a custom epoch and bit-layout.
+    pub fn with_config(node_id: u16, config: SnowflakeConfig) -> Result<Snowflake, SnowflakeError> {
+        if node_id > config.max_node_id() {
+            return Err(SnowflakeError::NodeIdOutOfRange);
+        }
+
+        let max_clock_seq = config.max_clock_seq();
+        let clock_seq = if max_clock_seq > 0 {
+            (clock::random_u16() as u32 % (max_clock_seq as u32 + 1)) as u16
+        } else {
+            0
+        };
 
-    let node_id = (id & 0x3FF000) >> 12;
-    let seq_num = id & 0xFFF;
-    let ts = (id & 0x7FFFFFFFFFC00000) >> 22;
+        let (datacenter_id, worker_id) = split_node(node_id, &config);
 
-    Snowflake {
-        node_id: node_id.try_into().unwrap(),
-        seq_num: seq_num.try_into().unwrap(),
-        ts
+        Ok(Snowflake {
+            node_id,
+            datacenter_id,
+            worker_id,
+            seq_num: 0,
+            ts: 0,
+            clock_seq,
+            config,
+            last_time: 0,
+        })
     }
-}
 
-impl Snowflake {
+    /// Create a generator by composing a `node_id` from a `datacenter_id` and
+    /// `worker_id`, splitting the classic 10-bit node field evenly in half (5
+    /// bits each), the original Twitter Snowflake convention. Use
+    /// [`Snowflake::with_datacenter_and_config`] for a different split.
+    pub fn with_datacenter(datacenter_id: u16, worker_id: u16) -> Result<Snowflake, SnowflakeError> {
+        let config = SnowflakeConfig::builder().datacenter_bits(5).build()?;
+        Snowflake::with_datacenter_and_config(datacenter_id, worker_id, config)
+    }
+
+    /// Create a generator by composing a `node_id` from a `datacenter_id` and
+    /// `worker_id`, per `config`'s `datacenter_bits`/`worker_bits` split.
+    pub fn with_datacenter_and_config(datacenter_id: u16, worker_id: u16, config: SnowflakeConfig) -> Result<Snowflake, SnowflakeError> {
+        if datacenter_id > config.max_datacenter_id() || worker_id > config.max_worker_id() {
+            return Err(SnowflakeError::NodeIdOutOfRange);
+        }
+
+        let node_id = datacenter_id.checked_shl(config.worker_bits() as u32).unwrap_or(0) | worker_id;
+        Snowflake::with_config(node_id, config)
+    }
+
+    /// Parse an id produced by a generator using this generator's config.
+    pub fn parse_id(&self, id: u64) -> Snowflake {
+        let node_mask = (1u64 << self.config.node_bits()) - 1;
+        let clock_seq_mask = (1u64 << self.config.clock_seq_bits()) - 1;
+        let seq_mask = (1u64 << self.config.sequence_bits()) - 1;
+
+        let node_id = (id >> (self.config.clock_seq_bits() + self.config.sequence_bits())) & node_mask;
+        let clock_seq = (id >> self.config.sequence_bits()) & clock_seq_mask;
+        let seq_num = id & seq_mask;
+        let ts = id >> (self.config.node_bits() + self.config.clock_seq_bits() + self.config.sequence_bits());
+        let node_id: u16 = node_id.try_into().unwrap();
+        let (datacenter_id, worker_id) = split_node(node_id, &self.config);
 
-    pub fn new(node_id: u16) -> Snowflake {
-        assert!(node_id < 1024);
         Snowflake {
-            node_id: node_id,
-            seq_num: 0,
-            ts: 0
+            node_id,
+            datacenter_id,
+            worker_id,
+            seq_num: seq_num.try_into().unwrap(),
+            clock_seq: clock_seq.try_into().unwrap(),
+            ts,
+            config: self.config,
+            last_time: 0,
         }
     }
 
-    pub fn generate(&mut self) -> u64 {
-        let sys_time = clock::get_time();
+    /// Generate the next id, surfacing clock and overflow failures instead of panicking.
+    pub fn try_generate(&mut self) -> Result<u64, SnowflakeError> {
+        let mut sys_time = clock::get_time(self.config.epoch())?;
+        let max_seq_num = self.config.max_seq_num();
+        let max_clock_seq = self.config.max_clock_seq();
+        let max_ts = (1u64 << self.config.timestamp_bits()) - 1;
+
+        if let Some(max_drift) = self.config.max_backward_drift() {
+            if self.ts > sys_time {
+                if self.ts - sys_time > max_drift {
+                    return Err(SnowflakeError::ClockMovedBackwards);
+                }
+
+                // Within tolerance: slew-wait for the wall clock to catch back
+                // up to our last timestamp instead of silently clamping to it.
+                clock::wait_until(self.config.epoch(), self.ts)?;
+                sys_time = clock::get_time(self.config.epoch())?;
+            }
+        }
 
         if self.ts == sys_time {
-            self.seq_num = self.seq_num + 1;
-            if self.seq_num > MAX_SEQ_NUM {
-                clock::wait();
+            if self.seq_num >= max_seq_num {
+                // The sequence is exhausted for this millisecond. A genuine
+                // backwards jump (the clock now reads behind where we last
+                // saw it) is disambiguated via clock_seq instead of blocking
+                // forever; a mere stall (the clock hasn't moved, but hasn't
+                // gone backwards either) still blocks on `clock::wait()` like
+                // the no-clock-seq case, since otherwise every exhausted
+                // sequence during a throughput burst would wrongly get
+                // treated as a regression and reuse this millisecond's id space.
+                let next_time = clock::get_time(self.config.epoch())?;
+                if max_clock_seq > 0 && next_time < self.last_time {
+                    // Adopt the regressed time as current rather than
+                    // clamping ahead of it: a bump pays for the whole
+                    // regression window once, not once per id issued while
+                    // the clock stays behind (which would drain clock_seq at
+                    // one bump per call and eventually wrap into reuse).
+                    self.clock_seq = ((self.clock_seq as u32 + 1) % (max_clock_seq as u32 + 1)) as u16;
+                    self.last_time = next_time;
+                    self.ts = next_time;
+                } else {
+                    clock::wait();
+                    self.ts = u64::max(self.ts, clock::get_time(self.config.epoch())?);
+                    self.last_time = self.ts;
+                }
                 self.seq_num = 0;
-                self.ts = u64::max(self.ts, clock::get_time());
             } else {
+                self.seq_num += 1;
                 self.ts = u64::max(self.ts, sys_time);
+                self.last_time = self.ts;
             }
+        } else if max_clock_seq > 0 && sys_time < self.ts {
+            // A genuine backwards jump caught on this call's very first clock
+            // read (not merely during sequence exhaustion): disambiguate via
+            // clock_seq instead of clamping to `self.ts` and silently
+            // re-emitting (ts, 0). Adopt `sys_time` as current (rather than
+            // keeping `self.ts` clamped ahead of it) so the bump costs exactly
+            // one clock_seq value per regression: the next call then sees
+            // `ts == sys_time` and resumes advancing `seq_num` under the new
+            // clock_seq instead of re-entering this branch and bumping again.
+            self.clock_seq = ((self.clock_seq as u32 + 1) % (max_clock_seq as u32 + 1)) as u16;
+            self.ts = sys_time;
+            self.last_time = sys_time;
+            self.seq_num = 0;
         } else {
             self.ts = u64::max(self.ts, sys_time);
+            self.last_time = self.ts;
             self.seq_num = 0;
         }
 
+        if self.ts > max_ts {
+            return Err(SnowflakeError::TimestampOverflow);
+        }
+
+        Ok(create_id(self.ts, self.node_id, self.clock_seq, self.seq_num, &self.config))
+    }
 
-        create_id(self.ts, self.node_id, self.seq_num)
+    /// Generate the next id.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the clock reads a time before the generator's epoch or the
+    /// elapsed time overflows the configured timestamp field, or if, with
+    /// `max_backward_drift` set, the clock reads further behind the last
+    /// timestamp than tolerated. Use
+    /// [`Snowflake::try_generate`] for a non-panicking path.
+    pub fn generate(&mut self) -> u64 {
+        self.try_generate().expect("snowflake generation failed")
     }
 
 }
@@ -71,8 +221,13 @@ impl Default for Snowflake {
     fn default() -> Self {
         Snowflake {
             node_id: 1,
+            datacenter_id: 0,
+            worker_id: 1,
             seq_num: 0,
-            ts: 0
+            ts: 0,
+            clock_seq: 0,
+            config: SnowflakeConfig::default(),
+            last_time: 0,
         }
     }
 }
@@ -81,7 +236,7 @@ impl Iterator for Snowflake {
     type Item = u64;
 
     fn next(&mut self) -> Option<Self::Item> {
-        Some(self.generate())
+        self.try_generate().ok()
     }
 }
 
@@ -89,13 +244,13 @@ impl Iterator for Snowflake {
 
 #[cfg(test)]
 mod tests {
-    use crate::{Snowflake, create_id, parse_id};
+    use crate::{Snowflake, SnowflakeConfig, SnowflakeError, create_id};
 
     #[test]
     fn round_trip() {
-        let mut s = Snowflake::new(1);
+        let mut s = Snowflake::new(1).unwrap();
         let id = s.generate();
-        let result = parse_id(id);
+        let result = s.parse_id(id);
         assert_eq!(s.node_id, result.node_id);
         assert_eq!(s.seq_num, result.seq_num);
         assert_eq!(s.ts, result.ts);
@@ -103,11 +258,12 @@ mod tests {
 
     #[test]
     fn test_create_id() {
-        let id = create_id(123456789, 777, 1234);
+        let config = SnowflakeConfig::default();
+        let id = create_id(123456789, 777, 0, 1234, &config);
 
         assert_eq!(id, 517815307113682);
 
-        let s = parse_id(id);
+        let s = Snowflake::new(0).unwrap().parse_id(id);
 
         assert_eq!(123456789, s.ts);
         assert_eq!(777, s.node_id);
@@ -118,22 +274,22 @@ mod tests {
     #[test]
     fn test_ms_rollover() {
         crate::clock::setup_mock_clock();
-        let mut s = Snowflake::new(123);
+        let mut s = Snowflake::new(123).unwrap();
 
         let first_id = s.generate();
-        let first_id_parsed = parse_id(first_id);
+        let first_id_parsed = s.parse_id(first_id);
 
         for seq in 1..4096 {
             let id = s.generate();
 
-            let result = parse_id(id);
+            let result = s.parse_id(id);
             assert_eq!(seq, result.seq_num);
             assert_eq!(first_id_parsed.ts, result.ts);
         }
 
         let rolled_over_id = s.generate();
 
-        let rolled_parsed = parse_id(rolled_over_id);
+        let rolled_parsed = s.parse_id(rolled_over_id);
 
         assert_eq!(first_id_parsed.ts + 1, rolled_parsed.ts);
     }
@@ -141,7 +297,7 @@ mod tests {
 
     #[test]
     fn test_many() {
-        let mut s = Snowflake::new(5);
+        let mut s = Snowflake::new(5).unwrap();
         let mut last = s.generate();
 
         let ids = s.take(1000000);
@@ -150,5 +306,238 @@ mod tests {
             last = id;
         }
     }
-    
+
+    #[test]
+    fn custom_layout_round_trips() {
+        let config = SnowflakeConfig::builder()
+            .timestamp_bits(40)
+            .node_bits(8)
+            .sequence_bits(8)
+            .build()
+            .unwrap();
+
+        let mut s = Snowflake::with_config(200, config).unwrap();
+        let id = s.generate();
+        let result = s.parse_id(id);
+        assert_eq!(result.node_id, 200);
+    }
+
+    #[test]
+    fn new_errors_when_node_id_out_of_range() {
+        let err = Snowflake::with_config(1024, SnowflakeConfig::default()).unwrap_err();
+        assert_eq!(err, SnowflakeError::NodeIdOutOfRange);
+    }
+
+    #[test]
+    fn with_datacenter_splits_the_node_field_in_half() {
+        let mut s = Snowflake::with_datacenter(5, 12).unwrap();
+        assert_eq!(s.node_id, (5 << 5) | 12);
+
+        let id = s.generate();
+        let result = s.parse_id(id);
+        assert_eq!(result.datacenter_id, 5);
+        assert_eq!(result.worker_id, 12);
+    }
+
+    #[test]
+    fn with_datacenter_composes_and_parses_node_id() {
+        let config = SnowflakeConfig::builder()
+            .node_bits(10)
+            .datacenter_bits(4)
+            .build()
+            .unwrap();
+
+        let mut s = Snowflake::with_datacenter_and_config(5, 12, config).unwrap();
+        assert_eq!(s.node_id, (5 << config.worker_bits()) | 12);
+
+        let id = s.generate();
+        let result = s.parse_id(id);
+        assert_eq!(result.datacenter_id, 5);
+        assert_eq!(result.worker_id, 12);
+    }
+
+    #[test]
+    fn with_datacenter_rejects_out_of_range_components() {
+        let config = SnowflakeConfig::builder()
+            .node_bits(10)
+            .datacenter_bits(4)
+            .build()
+            .unwrap();
+
+        let err = Snowflake::with_datacenter_and_config(16, 0, config).unwrap_err();
+        assert_eq!(err, SnowflakeError::NodeIdOutOfRange);
+    }
+
+    #[test]
+    fn clock_seq_stall_blocks_instead_of_bumping() {
+        crate::clock::setup_mock_clock();
+
+        let config = SnowflakeConfig::builder()
+            .timestamp_bits(41)
+            .node_bits(10)
+            .clock_seq_bits(4)
+            .sequence_bits(8)
+            .build()
+            .unwrap();
+
+        let mut s = Snowflake::with_config(1, config).unwrap();
+        s.generate(); // establish a baseline timestamp
+        let starting_ts = s.ts;
+        let starting_clock_seq = s.clock_seq;
+
+        // Exhaust the per-ms sequence without the mock clock ever moving: a
+        // stall, not a genuine backwards jump. It must block (advancing the
+        // clock via `wait`) rather than disambiguate via `clock_seq`, or every
+        // exhausted sequence during a burst would collide with ids already
+        // handed out for this millisecond.
+        for _ in 0..=config.max_seq_num() {
+            s.generate();
+        }
+
+        assert_eq!(s.clock_seq, starting_clock_seq);
+        assert_eq!(s.ts, starting_ts + 1);
+    }
+
+    #[test]
+    fn clock_seq_bumps_on_backward_jump_during_sequence_exhaustion() {
+        crate::clock::setup_mock_clock();
+
+        let config = SnowflakeConfig::builder()
+            .timestamp_bits(41)
+            .node_bits(10)
+            .clock_seq_bits(4)
+            .sequence_bits(4)
+            .build()
+            .unwrap();
+
+        let mut s = Snowflake::with_config(1, config).unwrap();
+        s.clock_seq = 0;
+
+        s.generate(); // establish a baseline timestamp
+        let starting_ts = s.ts;
+
+        // Exhaust the per-ms sequence at the same mocked timestamp.
+        let mut last_id = 0;
+        for _ in 0..config.max_seq_num() {
+            last_id = s.generate();
+        }
+        assert_eq!(s.ts, starting_ts);
+        assert_eq!(s.seq_num, config.max_seq_num());
+
+        // Script the next call's two internal clock reads: the first still
+        // matches `ts` (so the overflowed-sequence branch runs), the second -
+        // taken once the sequence has overflowed - reads behind `last_time`,
+        // a genuine regression sneaking in between the two reads of a single
+        // call. It must be disambiguated via `clock_seq` rather than quietly
+        // reused.
+        crate::clock::queue_mock_time(starting_ts);
+        crate::clock::queue_mock_time(starting_ts - 5);
+
+        let bumped_id = s.try_generate().unwrap();
+
+        // The regressed time is adopted as current (not clamped ahead of it),
+        // so the bump costs exactly one clock_seq value for this regression.
+        assert_eq!(s.clock_seq, 1);
+        assert_eq!(s.seq_num, 0);
+        assert_eq!(s.ts, starting_ts - 5);
+        assert_eq!(s.parse_id(bumped_id).ts, starting_ts - 5);
+        assert_ne!(bumped_id, last_id);
+
+        // A further call while the clock stays at the regressed time resumes
+        // advancing seq_num under the bumped clock_seq instead of bumping again.
+        crate::clock::set_mock_time(starting_ts - 5);
+        let next_id = s.try_generate().unwrap();
+        assert_eq!(s.clock_seq, 1);
+        let next = s.parse_id(next_id);
+        assert_eq!(next.ts, starting_ts - 5);
+        assert_eq!(next.seq_num, 1);
+    }
+
+    #[test]
+    fn clock_seq_bumps_on_backward_jump_caught_before_exhaustion() {
+        crate::clock::setup_mock_clock();
+
+        let config = SnowflakeConfig::builder()
+            .timestamp_bits(41)
+            .node_bits(10)
+            .clock_seq_bits(4)
+            .sequence_bits(8)
+            .build()
+            .unwrap();
+
+        let mut s = Snowflake::with_config(1, config).unwrap();
+        s.clock_seq = 0;
+
+        let first_id = s.generate();
+        let first_ts = s.parse_id(first_id).ts;
+
+        // Simulate the wall clock jumping back, caught on the very first
+        // clock read of the next call, well before the sequence would
+        // exhaust.
+        crate::clock::set_mock_time(first_ts - 5);
+
+        let second_id = s.try_generate().unwrap();
+
+        // The regressed time is adopted as current (not clamped ahead of it),
+        // so the bump costs exactly one clock_seq value for this regression
+        // rather than one per id issued while the clock stays behind.
+        assert_eq!(s.clock_seq, 1);
+        assert_eq!(s.seq_num, 0);
+        assert_eq!(s.ts, first_ts - 5);
+        assert_eq!(s.parse_id(second_id).ts, first_ts - 5);
+        assert_ne!(second_id, first_id);
+
+        // Further calls while the clock remains at the regressed time must
+        // not bump clock_seq again; a sustained regression still only costs
+        // one clock_seq value, not one per id issued during the window.
+        for expected_seq in 1..=3u16 {
+            let id = s.try_generate().unwrap();
+            assert_eq!(s.clock_seq, 1);
+            let parsed = s.parse_id(id);
+            assert_eq!(parsed.ts, first_ts - 5);
+            assert_eq!(parsed.seq_num, expected_seq);
+        }
+    }
+
+    #[test]
+    fn tolerated_backward_drift_slew_waits_instead_of_erroring() {
+        crate::clock::setup_mock_clock();
+
+        let config = SnowflakeConfig::builder()
+            .max_backward_drift(10)
+            .build()
+            .unwrap();
+
+        let mut s = Snowflake::with_config(1, config).unwrap();
+        let first_id = s.generate();
+        let first_ts = s.parse_id(first_id).ts;
+
+        // Simulate the wall clock jumping back within tolerance.
+        crate::clock::set_mock_time(first_ts - 3);
+
+        let id = s.try_generate().unwrap();
+        let result = s.parse_id(id);
+        assert_eq!(result.ts, first_ts);
+    }
+
+    #[test]
+    fn exceeded_backward_drift_errors() {
+        crate::clock::setup_mock_clock();
+
+        let config = SnowflakeConfig::builder()
+            .max_backward_drift(10)
+            .build()
+            .unwrap();
+
+        let mut s = Snowflake::with_config(1, config).unwrap();
+        let first_id = s.generate();
+        let first_ts = s.parse_id(first_id).ts;
+
+        // Simulate the wall clock jumping back further than tolerated.
+        crate::clock::set_mock_time(first_ts - 20);
+
+        let err = s.try_generate().unwrap_err();
+        assert_eq!(err, SnowflakeError::ClockMovedBackwards);
+    }
+
 }